@@ -1,7 +1,24 @@
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC};
 use thiserror::Error;
 
 const CRLF: [u8; 2] = [0x0D, 0x0A];
 
+/// Characters that may be left unescaped in a Gemini URL query: the
+/// "unreserved" set from RFC 3986 (letters, digits, `-`, `.`, `_`, `~`).
+/// Everything else -- including the reserved characters `?`, `#`, `&` and
+/// spaces -- is percent-encoded.
+const QUERY_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Percent-encodes `input` for use as a Gemini URL query component, e.g. the
+/// answer to a status 1x INPUT prompt.
+pub fn encode_query(input: &str) -> String {
+    percent_encoding::utf8_percent_encode(input, QUERY_ENCODE_SET).to_string()
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Header {
     pub status: String,
@@ -11,7 +28,27 @@ pub struct Header {
 #[derive(Debug, PartialEq)]
 pub struct Response {
     pub header: Header,
-    pub body: String,
+    pub body: Vec<u8>,
+}
+
+/// Splits a response header's `meta` field into its MIME type and an
+/// optional `charset` parameter, e.g. `text/plain; charset=utf-8` ->
+/// `("text/plain", Some("utf-8"))`.
+pub fn parse_mime(meta: &str) -> (&str, Option<&str>) {
+    let mut parts = meta.split(';');
+    let mime = parts.next().unwrap_or("").trim();
+    let charset = parts.find_map(|param| {
+        param
+            .trim()
+            .strip_prefix("charset=")
+            .map(|v| v.trim_matches('"'))
+    });
+    (mime, charset)
+}
+
+/// Whether a MIME type's body can meaningfully be treated as text.
+pub fn is_textual(mime: &str) -> bool {
+    mime.starts_with("text/")
 }
 
 #[derive(Error, Debug, PartialEq)]
@@ -90,10 +127,10 @@ pub fn parse_response(plaintext: &[u8]) -> Result<Response, GeminiError> {
 
     let header = parse_header(&raw_header)?;
 
-    // It is assumed that the response body is UTF-8 decodable.
-    // TODO(pgold): consider checking whether the header for the encoding and
-    // act accordingly.
-    let body = std::str::from_utf8(raw_body)?.to_string();
+    // The body is kept as raw bytes: it may be non-UTF-8 text (a non-"utf-8"
+    // charset) or binary (an "image/*" or other non-textual MIME type), and
+    // it is up to the caller to decide whether and how to decode it.
+    let body = raw_body.to_vec();
     Ok(Response { header, body })
 }
 
@@ -110,9 +147,31 @@ fn find_first(haystack: &[u8], needle: &[u8]) -> Option<usize> {
 
 #[cfg(test)]
 mod tests {
+    use super::encode_query;
+    use super::is_textual;
+    use super::parse_mime;
     use super::parse_response;
     use super::GeminiError;
 
+    #[test]
+    fn encode_query_escapes_reserved_characters() {
+        assert_eq!(encode_query("hello world"), "hello%20world");
+        assert_eq!(encode_query("a?b#c&d"), "a%3Fb%23c%26d");
+        assert_eq!(encode_query("unreserved-._~ok"), "unreserved-._~ok");
+    }
+
+    #[test]
+    fn parse_mime_splits_type_and_charset() {
+        assert_eq!(parse_mime("text/gemini"), ("text/gemini", None));
+        assert_eq!(
+            parse_mime("text/plain; charset=utf-8"),
+            ("text/plain", Some("utf-8"))
+        );
+        assert_eq!(parse_mime("image/png"), ("image/png", None));
+        assert!(is_textual("text/plain"));
+        assert!(!is_textual("image/png"));
+    }
+
     #[test]
     fn parse_response_happy() -> Result<(), GeminiError> {
         parse_response("20 text/gemini\r\n".as_bytes())?;
@@ -0,0 +1,181 @@
+//! Parsing and rendering of `text/gemini` bodies.
+
+/// A single line of a parsed gemtext document.
+#[derive(Debug, PartialEq)]
+pub enum Line {
+    Text(String),
+    Link { url: String, label: Option<String> },
+    Heading { level: u8, text: String },
+    ListItem(String),
+    Quote(String),
+    Preformatted { alt: Option<String>, lines: Vec<String> },
+}
+
+/// A gemtext document: an ordered sequence of lines.
+#[derive(Debug, PartialEq, Default)]
+pub struct Document {
+    pub lines: Vec<Line>,
+}
+
+/// Parses a `text/gemini` body into a `Document`.
+pub fn parse(body: &str) -> Document {
+    let mut lines = Vec::new();
+    let mut raw_lines = body.lines();
+
+    while let Some(line) = raw_lines.next() {
+        if let Some(alt) = line.strip_prefix("```") {
+            let alt = if alt.is_empty() { None } else { Some(alt.to_string()) };
+            let mut preformatted = Vec::new();
+            for pre_line in raw_lines.by_ref() {
+                if pre_line.starts_with("```") {
+                    break;
+                }
+                preformatted.push(pre_line.to_string());
+            }
+            lines.push(Line::Preformatted { alt, lines: preformatted });
+        } else if let Some(rest) = line.strip_prefix("=>") {
+            lines.push(parse_link(rest));
+        } else if let Some(rest) = line.strip_prefix("###") {
+            lines.push(Line::Heading { level: 3, text: rest.trim_start().to_string() });
+        } else if let Some(rest) = line.strip_prefix("##") {
+            lines.push(Line::Heading { level: 2, text: rest.trim_start().to_string() });
+        } else if let Some(rest) = line.strip_prefix('#') {
+            lines.push(Line::Heading { level: 1, text: rest.trim_start().to_string() });
+        } else if let Some(rest) = line.strip_prefix("* ") {
+            lines.push(Line::ListItem(rest.to_string()));
+        } else if let Some(rest) = line.strip_prefix('>') {
+            lines.push(Line::Quote(rest.trim_start().to_string()));
+        } else {
+            lines.push(Line::Text(line.to_string()));
+        }
+    }
+
+    Document { lines }
+}
+
+fn parse_link(rest: &str) -> Line {
+    let rest = rest.trim_start();
+    match rest.split_once(char::is_whitespace) {
+        Some((url, label)) => {
+            let label = label.trim();
+            Line::Link {
+                url: url.to_string(),
+                label: if label.is_empty() { None } else { Some(label.to_string()) },
+            }
+        }
+        None => Line::Link { url: rest.to_string(), label: None },
+    }
+}
+
+/// Renders a `Document` as a readable terminal layout, numbering links so
+/// they can be followed (e.g. by an interactive browser mode).
+pub fn render(doc: &Document) -> String {
+    let mut out = String::new();
+    let mut link_index = 1;
+
+    for line in &doc.lines {
+        match line {
+            Line::Text(text) => out.push_str(text),
+            Line::Heading { level, text } => {
+                out.push_str(&"#".repeat(*level as usize));
+                out.push(' ');
+                out.push_str(text);
+            }
+            Line::ListItem(text) => {
+                out.push_str("* ");
+                out.push_str(text);
+            }
+            Line::Quote(text) => {
+                out.push_str("> ");
+                out.push_str(text);
+            }
+            Line::Link { url, label } => {
+                out.push_str(&format!("[{}] ", link_index));
+                out.push_str(label.as_deref().unwrap_or(url));
+                link_index += 1;
+            }
+            Line::Preformatted { lines, .. } => {
+                for pre_line in lines {
+                    out.push_str(pre_line);
+                    out.push('\n');
+                }
+                if !lines.is_empty() {
+                    out.pop();
+                }
+            }
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, render, Document, Line};
+
+    #[test]
+    fn parse_covers_all_line_kinds() {
+        let body = "# heading\n\
+                     ## sub\n\
+                     ### subsub\n\
+                     => gemini://example.com/ an example\n\
+                     => gemini://example.com/\n\
+                     * item\n\
+                     > quoted\n\
+                     plain text\n\
+                     ```alt text\n\
+                     line one\n\
+                     line two\n\
+                     ```\n";
+        let doc = parse(body);
+        assert_eq!(
+            doc,
+            Document {
+                lines: vec![
+                    Line::Heading { level: 1, text: "heading".to_string() },
+                    Line::Heading { level: 2, text: "sub".to_string() },
+                    Line::Heading { level: 3, text: "subsub".to_string() },
+                    Line::Link {
+                        url: "gemini://example.com/".to_string(),
+                        label: Some("an example".to_string()),
+                    },
+                    Line::Link { url: "gemini://example.com/".to_string(), label: None },
+                    Line::ListItem("item".to_string()),
+                    Line::Quote("quoted".to_string()),
+                    Line::Text("plain text".to_string()),
+                    Line::Preformatted {
+                        alt: Some("alt text".to_string()),
+                        lines: vec!["line one".to_string(), "line two".to_string()],
+                    },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn render_numbers_links() {
+        let doc = Document {
+            lines: vec![
+                Line::Link { url: "gemini://a/".to_string(), label: Some("A".to_string()) },
+                Line::Link { url: "gemini://b/".to_string(), label: None },
+            ],
+        };
+        assert_eq!(render(&doc), "[1] A\n[2] gemini://b/\n");
+    }
+
+    #[test]
+    fn parse_link_with_trailing_whitespace_has_no_label() {
+        let doc = parse("=> gemini://example.com/  \n");
+        assert_eq!(
+            doc.lines,
+            vec![Line::Link { url: "gemini://example.com/".to_string(), label: None }],
+        );
+    }
+
+    #[test]
+    fn render_empty_preformatted_block_does_not_eat_preceding_output() {
+        let doc = parse("hello\n```\n```\nworld\n");
+        assert_eq!(render(&doc), "hello\n\nworld\n");
+    }
+}
@@ -0,0 +1,140 @@
+//! Client-certificate identities, as requested by Gemini capsules via
+//! status 6x responses.
+
+use std::fs;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use rcgen::{Certificate as RcgenCertificate, CertificateParams, DistinguishedName, DnType};
+
+const CERT_FILE_NAME: &str = "identity.crt";
+const KEY_FILE_NAME: &str = "identity.key";
+
+/// A client certificate and its private key, ready to install into a
+/// `rustls::ClientConfig` via `set_single_client_cert`.
+pub struct Identity {
+    pub cert_chain: Vec<rustls::Certificate>,
+    pub private_key: rustls::PrivateKey,
+}
+
+impl Identity {
+    /// Loads a cert/key PEM pair from disk.
+    pub fn from_pem_files(cert_path: &Path, key_path: &Path) -> Result<Self> {
+        let cert_chain = {
+            let file = fs::File::open(cert_path)
+                .with_context(|| format!("failed to open {}", cert_path.display()))?;
+            rustls::internal::pemfile::certs(&mut BufReader::new(file))
+                .map_err(|_| anyhow!("failed to parse certificate {}", cert_path.display()))?
+        };
+
+        let private_key = {
+            let file = fs::File::open(key_path)
+                .with_context(|| format!("failed to open {}", key_path.display()))?;
+            let mut keys = rustls::internal::pemfile::pkcs8_private_keys(&mut BufReader::new(file))
+                .map_err(|_| anyhow!("failed to parse private key {}", key_path.display()))?;
+            keys.pop()
+                .with_context(|| format!("no private key found in {}", key_path.display()))?
+        };
+
+        Ok(Identity { cert_chain, private_key })
+    }
+
+    /// Loads the identity named `name` for `host`/`path` from `identity_dir`.
+    ///
+    /// Identities are stored one directory per path segment, under
+    /// `identity_dir/name/host/segment/segment/...`, so that a request under
+    /// a protected subtree reuses whichever identity was established for the
+    /// longest matching path prefix, rather than minting an unrelated
+    /// identity per exact path -- matching how Gemini capsules scope client
+    /// certificates to a path and everything beneath it.
+    pub fn for_scope(identity_dir: &Path, name: &str, host: &str, path: &str) -> Result<Self> {
+        let base = identity_dir.join(name).join(sanitize_segment(host));
+        let segments: Vec<String> = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(sanitize_segment)
+            .collect();
+
+        // Search from the most specific path down to the root for an
+        // identity already established for an ancestor of this path.
+        for depth in (0..=segments.len()).rev() {
+            let dir = segments[..depth].iter().fold(base.clone(), |dir, segment| dir.join(segment));
+            let (cert_path, key_path) = (dir.join(CERT_FILE_NAME), dir.join(KEY_FILE_NAME));
+            if cert_path.exists() && key_path.exists() {
+                return Self::from_pem_files(&cert_path, &key_path);
+            }
+        }
+
+        // No identity covers this path yet; mint one scoped to it.
+        let dir = segments.iter().fold(base, |dir, segment| dir.join(segment));
+        generate_and_persist(&dir, name, host, path)?;
+        Self::from_pem_files(&dir.join(CERT_FILE_NAME), &dir.join(KEY_FILE_NAME))
+    }
+}
+
+fn generate_and_persist(dir: &Path, name: &str, host: &str, path: &str) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+
+    let mut params = CertificateParams::new(Vec::new());
+    let mut distinguished_name = DistinguishedName::new();
+    distinguished_name.push(DnType::CommonName, format!("{}/{}{}", name, host, path));
+    params.distinguished_name = distinguished_name;
+
+    let cert = RcgenCertificate::from_params(params)
+        .with_context(|| "failed to generate client certificate")?;
+
+    let cert_path = dir.join(CERT_FILE_NAME);
+    let key_path = dir.join(KEY_FILE_NAME);
+
+    fs::write(
+        &cert_path,
+        cert.serialize_pem()
+            .with_context(|| "failed to serialize client certificate")?,
+    )
+    .with_context(|| format!("failed to write {}", cert_path.display()))?;
+    fs::write(&key_path, cert.serialize_private_key_pem())
+        .with_context(|| format!("failed to write {}", key_path.display()))?;
+
+    Ok(())
+}
+
+/// Turns a single path segment (or host) into a filesystem-safe directory name.
+fn sanitize_segment(segment: &str) -> String {
+    segment
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sanitize_segment, Identity};
+
+    #[test]
+    fn sanitize_segment_replaces_non_alphanumeric_characters() {
+        assert_eq!(sanitize_segment("exa mple.com:1965"), "exa_mple_com_1965");
+    }
+
+    #[test]
+    fn for_scope_reuses_ancestor_identity_for_a_deeper_path() {
+        let dir = std::env::temp_dir().join(format!("gget-identity-test-ancestor-{}", std::process::id()));
+
+        let root = Identity::for_scope(&dir, "default", "example.com", "/private").unwrap();
+        let nested = Identity::for_scope(&dir, "default", "example.com", "/private/docs").unwrap();
+        assert_eq!(nested.cert_chain, root.cert_chain);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn for_scope_does_not_reuse_identity_for_a_sibling_path() {
+        let dir = std::env::temp_dir().join(format!("gget-identity-test-sibling-{}", std::process::id()));
+
+        let first = Identity::for_scope(&dir, "default", "example.com", "/private").unwrap();
+        let sibling = Identity::for_scope(&dir, "default", "example.com", "/other").unwrap();
+        assert_ne!(sibling.cert_chain, first.cert_chain);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
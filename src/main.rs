@@ -1,6 +1,8 @@
-use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{anyhow, Context, Result};
 
@@ -8,94 +10,333 @@ use structopt::StructOpt;
 use url::Url;
 
 mod gemini;
+mod gemtext;
+mod identity;
+mod network;
 mod verifier;
 
-const DEFAULT_PORT: u16 = 1965;
+/// How the server's TLS certificate should be authenticated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CertMode {
+    /// Trust-on-first-use: pin the certificate seen on first contact with a
+    /// host and require it (or its replacement after expiry) thereafter.
+    /// This is how most Gemini clients and servers operate.
+    Tofu,
+    /// Validate against the standard webpki CA root store, as in the web PKI.
+    Ca,
+    /// Accept any certificate without verification.
+    Insecure,
+}
+
+impl FromStr for CertMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "tofu" => Ok(CertMode::Tofu),
+            "ca" => Ok(CertMode::Ca),
+            "insecure" => Ok(CertMode::Insecure),
+            _ => Err(anyhow!("unknown certificate mode \"{}\" (expected tofu, ca, or insecure)", s)),
+        }
+    }
+}
+
+fn default_known_hosts_path() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("gget")
+        .join("known_hosts")
+}
+
+fn default_identity_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("gget")
+        .join("identities")
+}
+
+/// Derives a client-certificate scope (host and path) from a request URL.
+fn cert_scope(url: &str) -> Result<(String, String)> {
+    let parsed = Url::parse(url).with_context(|| "invalid URL")?;
+    let host = parsed.host_str().with_context(|| "invalid host")?.to_string();
+    Ok((host, parsed.path().to_string()))
+}
+
+/// Options shared by every subcommand.
+#[derive(StructOpt)]
+struct CommonArgs {
+    #[structopt(long, default_value = "10")]
+    max_redirects: u32,
+
+    /// How to authenticate the server's TLS certificate: tofu, ca, or insecure.
+    #[structopt(long, default_value = "tofu")]
+    cert_mode: CertMode,
+
+    /// Path to the TOFU trust store (only used in tofu mode).
+    #[structopt(long, parse(from_os_str))]
+    known_hosts: Option<PathBuf>,
+
+    /// Name of the client-certificate identity to present when a server
+    /// requests one (status 6x). Identities are generated on demand and
+    /// persisted under a local identity directory, scoped per host/path.
+    #[structopt(long, default_value = "default")]
+    identity: String,
+
+    /// Use an existing client certificate instead of one generated for
+    /// --identity. Must be paired with --key-path.
+    #[structopt(long, parse(from_os_str))]
+    cert_path: Option<PathBuf>,
+
+    /// Private key matching --cert-path.
+    #[structopt(long, parse(from_os_str))]
+    key_path: Option<PathBuf>,
+
+    /// Timeout, in seconds, for establishing the TCP connection and TLS handshake.
+    #[structopt(long, default_value = "10")]
+    connect_timeout: u64,
+
+    /// Timeout, in seconds, for reading the full response.
+    #[structopt(long, default_value = "30")]
+    read_timeout: u64,
+}
+
+impl CommonArgs {
+    fn build_config(&self) -> Result<rustls::ClientConfig> {
+        let known_hosts_path = self.known_hosts.clone().unwrap_or_else(default_known_hosts_path);
+        rustls_config(self.cert_mode, known_hosts_path)
+    }
+
+    fn build_config_with_identity(&self, identity: &identity::Identity) -> Result<rustls::ClientConfig> {
+        let mut config = self.build_config()?;
+        config
+            .set_single_client_cert(identity.cert_chain.clone(), identity.private_key.clone())
+            .with_context(|| "failed to install client certificate")?;
+        Ok(config)
+    }
+
+    fn load_identity(&self, host: &str, path: &str) -> Result<identity::Identity> {
+        match (&self.cert_path, &self.key_path) {
+            (Some(cert_path), Some(key_path)) => identity::Identity::from_pem_files(cert_path, key_path),
+            _ => identity::Identity::for_scope(&default_identity_dir(), &self.identity, host, path),
+        }
+    }
+}
 
 #[derive(StructOpt)]
-struct Cli {
+struct FetchArgs {
     /// The URL to be fetched.
     url: String,
 
-    #[structopt(long, default_value = "10")]
-    max_redirects: u32,
+    #[structopt(flatten)]
+    common: CommonArgs,
 
-    #[structopt(long)]
-    validate_certificate: bool,
+    /// Write the response body to this path instead of printing it.
+    #[structopt(long, parse(from_os_str))]
+    output: Option<PathBuf>,
 }
 
-fn rustls_config(validate_certificate: bool) -> rustls::ClientConfig {
+#[derive(StructOpt)]
+struct BrowseArgs {
+    /// The URL to start browsing from.
+    url: String,
+
+    #[structopt(flatten)]
+    common: CommonArgs,
+}
+
+#[derive(StructOpt)]
+enum Cli {
+    /// Fetch a single URL and print or save its response.
+    Fetch(FetchArgs),
+    /// Interactively browse pages, following numbered links.
+    Browse(BrowseArgs),
+}
+
+fn rustls_config(cert_mode: CertMode, known_hosts_path: PathBuf) -> Result<rustls::ClientConfig> {
     let mut config = rustls::ClientConfig::new();
-    match validate_certificate {
-        true => config
+    match cert_mode {
+        CertMode::Ca => config
             .root_store
             .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS),
-        false => config
+        CertMode::Insecure => config
             .dangerous()
             .set_certificate_verifier(Arc::new(verifier::NullVerifier {})),
+        CertMode::Tofu => config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(verifier::TofuVerifier::new(known_hosts_path)?)),
     }
-    config
+    Ok(config)
 }
 
-fn fetch(config: &Arc<rustls::ClientConfig>, url: &str) -> Result<gemini::Response> {
-    let url = Url::parse(url).with_context(|| "invalid URL")?;
+fn prompt_for_input(prompt: &str, sensitive: bool) -> Result<String> {
+    print!("{}: ", prompt);
+    std::io::stdout().flush().with_context(|| "failed to write prompt")?;
 
-    match url.scheme() {
-        "gemini" | "" => (),
-        s => return Err(anyhow!("unknown scheme \"{}\"", s)),
+    if sensitive {
+        rpassword::read_password().with_context(|| "failed to read sensitive input")
+    } else {
+        let mut line = String::new();
+        std::io::stdin()
+            .read_line(&mut line)
+            .with_context(|| "failed to read input")?;
+        Ok(line.trim_end_matches(&['\r', '\n'][..]).to_string())
     }
+}
+
+/// Returns `url` with its query component set to the percent-encoded
+/// answer to an INPUT prompt, ready for the request to be re-issued.
+fn with_query(url: &str, answer: &str) -> Result<String> {
+    let mut parsed = Url::parse(url).with_context(|| "invalid URL")?;
+    parsed.set_query(Some(&gemini::encode_query(answer)));
+    Ok(parsed.to_string())
+}
 
-    let host_str = url.host_str().with_context(|| "invalid host")?;
-    let port = url.port().unwrap_or(DEFAULT_PORT);
+/// Owns the async runtime and `network::Client` used to drive fetches, so
+/// both are prepared once and reused across every request a session makes
+/// (every redirect hop, and every page navigated to in `browse` mode)
+/// instead of being rebuilt per call. The client is rebuilt only when
+/// `install_identity` adds a client certificate to it.
+struct Fetcher {
+    runtime: tokio::runtime::Runtime,
+    client: network::Client,
+    connect_timeout: Duration,
+    read_timeout: Duration,
+}
 
-    let dns_name = webpki::DNSNameRef::try_from_ascii_str(host_str)?;
-    let mut sess = rustls::ClientSession::new(config, dns_name);
-    let mut stream = TcpStream::connect((host_str, port)).with_context(|| "connection failed")?;
-    let mut tls = rustls::Stream::new(&mut sess, &mut stream);
+impl Fetcher {
+    fn new(common: &CommonArgs) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .with_context(|| "failed to start async runtime")?;
+        let connect_timeout = Duration::from_secs(common.connect_timeout);
+        let read_timeout = Duration::from_secs(common.read_timeout);
+        let client = network::Client::new(Arc::new(common.build_config()?), connect_timeout, read_timeout);
+        Ok(Fetcher { runtime, client, connect_timeout, read_timeout })
+    }
 
-    let req = gemini::request(url.as_str());
-    tls.write(&req)
-        .with_context(|| "failed sending gemini request")?;
+    fn request(&self, url: &str) -> Result<gemini::Response> {
+        self.runtime.block_on(self.client.request(url))
+    }
 
-    let mut plaintext = Vec::new();
-    match tls.read_to_end(&mut plaintext) {
-        Ok(_) => (),
-        // Ignore ConnectionAborted -- this means that the server closed the
-        // connection after responding.
-        Err(ref e) if e.kind() == std::io::ErrorKind::ConnectionAborted => (),
-        Err(e) => Err(e).with_context(|| "TLS read error")?,
+    fn install_identity(&mut self, common: &CommonArgs, identity: &identity::Identity) -> Result<()> {
+        let config = Arc::new(common.build_config_with_identity(identity)?);
+        self.client = network::Client::new(config, self.connect_timeout, self.read_timeout);
+        Ok(())
     }
+}
 
-    Ok(gemini::parse_response(&plaintext).with_context(|| "failed to parse response")?)
+/// A single step `recursive_fetch` took before reaching a final response,
+/// kept only so the max-iterations error can say what it ran out of.
+#[derive(Debug, Clone, Copy)]
+enum FetchStep {
+    Redirect,
+    Input,
+    ClientCertificateRequired,
 }
 
-fn recursive_fetch(
-    config: &Arc<rustls::ClientConfig>,
-    url: &str,
-    max_redirects: u32,
-) -> Result<gemini::Response> {
-    let mut redirects = 0;
+impl std::fmt::Display for FetchStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchStep::Redirect => write!(f, "redirects"),
+            FetchStep::Input => write!(f, "input prompts"),
+            FetchStep::ClientCertificateRequired => write!(f, "client-certificate retries"),
+        }
+    }
+}
+
+/// Follows redirects, INPUT prompts, and client-certificate retries until a
+/// final response is reached. Up to `max_redirects` combined iterations of
+/// any of these kinds are followed; one more than that is an error.
+fn recursive_fetch(fetcher: &mut Fetcher, common: &CommonArgs, url: &str) -> Result<gemini::Response> {
+    let mut iterations = 0;
+    let mut last_step = None;
     let mut current_url = url.to_string();
-    while redirects <= max_redirects {
-        let response = fetch(config, &current_url)?;
+    while iterations <= common.max_redirects {
+        let response = fetcher.request(&current_url)?;
         match gemini::status_category(&response.header.status)? {
             gemini::StatusCategory::Redirect => {
-                redirects += 1;
+                iterations += 1;
+                last_step = Some(FetchStep::Redirect);
                 current_url = response.header.meta;
             }
+            gemini::StatusCategory::Input => {
+                iterations += 1;
+                last_step = Some(FetchStep::Input);
+                let sensitive = response.header.status == "11";
+                let answer = prompt_for_input(&response.header.meta, sensitive)?;
+                current_url = with_query(&current_url, &answer)?;
+            }
+            gemini::StatusCategory::ClientCertificateRequired => {
+                iterations += 1;
+                last_step = Some(FetchStep::ClientCertificateRequired);
+                let (host, path) = cert_scope(&current_url)?;
+                let identity = common.load_identity(&host, &path)?;
+                fetcher.install_identity(common, &identity)?;
+            }
             _ => return Ok(response),
         }
     }
-    Err(anyhow!("maximum redirects ({}) exceeded", max_redirects))
+    match last_step {
+        Some(step) => Err(anyhow!("maximum {} ({}) exceeded", step, common.max_redirects)),
+        None => Err(anyhow!("maximum iterations ({}) exceeded", common.max_redirects)),
+    }
 }
 
-fn main() -> Result<()> {
-    let args = Cli::from_args();
-    let config = Arc::new(rustls_config(args.validate_certificate));
-    let response = recursive_fetch(&config, &args.url, args.max_redirects)?;
+/// Picks a filename for an auto-saved binary response, based on the last
+/// path segment of the request URL.
+fn default_output_path(url: &str) -> PathBuf {
+    Url::parse(url)
+        .ok()
+        .and_then(|u| u.path_segments().and_then(|mut s| s.next_back().map(str::to_string)))
+        .filter(|name| !name.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("gget-download"))
+}
+
+/// Whether a response's MIME type (and optional charset) can safely be
+/// decoded and treated as UTF-8 text, rather than written/printed as raw
+/// bytes. Deciding this from the declared MIME type -- not from whether the
+/// bytes happen to parse as UTF-8 -- keeps e.g. `image/*` bodies that are
+/// coincidentally valid UTF-8 from being dumped to a terminal.
+fn is_utf8_text(meta: &str) -> bool {
+    let (mime, charset) = gemini::parse_mime(meta);
+    gemini::is_textual(mime) && charset.is_none_or(|c| c.eq_ignore_ascii_case("utf-8"))
+}
+
+/// Writes or prints a successful response's body, decoding it to text only
+/// when the MIME type is textual and UTF-8 encoded.
+fn output_body(response: &gemini::Response, url: &str, output: Option<&std::path::Path>) -> Result<()> {
+    let (mime, _) = gemini::parse_mime(&response.header.meta);
+
+    if let Some(path) = output {
+        return std::fs::write(path, &response.body)
+            .with_context(|| format!("failed to write {}", path.display()));
+    }
+
+    if !is_utf8_text(&response.header.meta) {
+        let path = default_output_path(url);
+        std::fs::write(&path, &response.body)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        eprintln!("saved binary response ({}) to {}", mime, path.display());
+        return Ok(());
+    }
+
+    let body = std::str::from_utf8(&response.body).with_context(|| "response body is not valid UTF-8")?;
+    if mime == "text/gemini" {
+        print!("{}", gemtext::render(&gemtext::parse(body)));
+    } else {
+        println!("{}", body);
+    }
+    Ok(())
+}
+
+fn run_fetch(args: FetchArgs) -> Result<()> {
+    let mut fetcher = Fetcher::new(&args.common)?;
+    let response = recursive_fetch(&mut fetcher, &args.common, &args.url)?;
 
     match gemini::status_category(&response.header.status)? {
-        gemini::StatusCategory::Success => println!("{}", response.body),
+        gemini::StatusCategory::Success => output_body(&response, &args.url, args.output.as_deref())?,
         _ => {
             return Err(anyhow!(
                 "{} - {}",
@@ -107,3 +348,118 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// Returns the URL of the `n`th link (1-indexed) in the document, matching
+/// the numbering `gemtext::render` prints alongside each link.
+fn nth_link_url(doc: &gemtext::Document, n: usize) -> Option<&str> {
+    doc.lines
+        .iter()
+        .filter_map(|line| match line {
+            gemtext::Line::Link { url, .. } => Some(url.as_str()),
+            _ => None,
+        })
+        .nth(n.checked_sub(1)?)
+}
+
+fn read_command() -> Result<Option<String>> {
+    print!("> ");
+    std::io::stdout().flush().with_context(|| "failed to write prompt")?;
+
+    let mut line = String::new();
+    let bytes_read = std::io::stdin()
+        .read_line(&mut line)
+        .with_context(|| "failed to read command")?;
+    if bytes_read == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+fn run_browse(args: BrowseArgs) -> Result<()> {
+    let mut fetcher = Fetcher::new(&args.common)?;
+    let mut current_url = args.url;
+    let mut history: Vec<String> = Vec::new();
+    let mut current_doc: Option<gemtext::Document> = None;
+
+    loop {
+        match recursive_fetch(&mut fetcher, &args.common, &current_url) {
+            Ok(response) => match gemini::status_category(&response.header.status)? {
+                gemini::StatusCategory::Success => {
+                    let (mime, _) = gemini::parse_mime(&response.header.meta);
+                    if !is_utf8_text(&response.header.meta) {
+                        eprintln!("(binary response, {}; use `fetch --output` to save it)", mime);
+                        current_doc = None;
+                    } else {
+                        match std::str::from_utf8(&response.body) {
+                            Ok(body) if mime == "text/gemini" => {
+                                let doc = gemtext::parse(body);
+                                print!("{}", gemtext::render(&doc));
+                                current_doc = Some(doc);
+                            }
+                            Ok(body) => {
+                                println!("{}", body);
+                                current_doc = None;
+                            }
+                            Err(e) => {
+                                eprintln!("error: response declared as {} but not valid UTF-8: {}", mime, e);
+                                current_doc = None;
+                            }
+                        }
+                    }
+                }
+                _ => eprintln!("{} - {}", response.header.status, response.header.meta),
+            },
+            Err(e) => eprintln!("error: {:#}", e),
+        }
+
+        let command = match read_command()? {
+            Some(command) => command,
+            None => break,
+        };
+        let mut tokens = command.split_whitespace();
+        let target = match tokens.next() {
+            None => continue,
+            Some("q") => break,
+            Some("b") => match history.pop() {
+                Some(previous) => {
+                    current_url = previous;
+                    continue;
+                }
+                None => {
+                    eprintln!("no previous page to go back to");
+                    continue;
+                }
+            },
+            Some(tok) if tok.starts_with("gemini://") => tok.to_string(),
+            Some(tok) => match tok.parse::<usize>() {
+                Ok(n) => match current_doc.as_ref().and_then(|doc| nth_link_url(doc, n)) {
+                    Some(link_url) => link_url.to_string(),
+                    None => {
+                        eprintln!("no such link: {}", n);
+                        continue;
+                    }
+                },
+                Err(_) => {
+                    eprintln!("unrecognized command: {}", tok);
+                    continue;
+                }
+            },
+        };
+
+        let resolved = Url::parse(&current_url)
+            .with_context(|| "invalid URL")?
+            .join(&target)
+            .with_context(|| format!("failed to resolve link \"{}\"", target))?;
+        history.push(current_url);
+        current_url = resolved.to_string();
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    match Cli::from_args() {
+        Cli::Fetch(args) => run_fetch(args),
+        Cli::Browse(args) => run_browse(args),
+    }
+}
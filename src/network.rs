@@ -0,0 +1,76 @@
+//! Async networking: a reusable `Client` that holds a prepared TLS config
+//! and connector so the handshake setup isn't redone on every request.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use url::Url;
+
+use crate::gemini;
+
+const DEFAULT_PORT: u16 = 1965;
+
+/// An async Gemini client, built once from a `rustls::ClientConfig` and
+/// reused across many requests.
+pub struct Client {
+    connector: tokio_rustls::TlsConnector,
+    connect_timeout: Duration,
+    read_timeout: Duration,
+}
+
+impl Client {
+    pub fn new(config: Arc<rustls::ClientConfig>, connect_timeout: Duration, read_timeout: Duration) -> Self {
+        Client {
+            connector: tokio_rustls::TlsConnector::from(config),
+            connect_timeout,
+            read_timeout,
+        }
+    }
+
+    /// Fetches `url`, performing the TLS handshake and read within the
+    /// configured connect/read timeouts so a dead or slow server can't hang
+    /// the client indefinitely.
+    pub async fn request(&self, url: &str) -> Result<gemini::Response> {
+        let parsed = Url::parse(url).with_context(|| "invalid URL")?;
+
+        match parsed.scheme() {
+            "gemini" | "" => (),
+            s => return Err(anyhow!("unknown scheme \"{}\"", s)),
+        }
+
+        let host_str = parsed.host_str().with_context(|| "invalid host")?;
+        let port = parsed.port().unwrap_or(DEFAULT_PORT);
+        let dns_name = webpki::DNSNameRef::try_from_ascii_str(host_str)?;
+
+        let tcp = timeout(self.connect_timeout, TcpStream::connect((host_str, port)))
+            .await
+            .with_context(|| "connection timed out")?
+            .with_context(|| "connection failed")?;
+
+        let mut tls = timeout(self.connect_timeout, self.connector.connect(dns_name, tcp))
+            .await
+            .with_context(|| "TLS handshake timed out")?
+            .with_context(|| "TLS handshake failed")?;
+
+        let req = gemini::request(parsed.as_str());
+        tls.write_all(&req)
+            .await
+            .with_context(|| "failed sending gemini request")?;
+
+        let mut plaintext = Vec::new();
+        match timeout(self.read_timeout, tls.read_to_end(&mut plaintext)).await {
+            Ok(Ok(_)) => (),
+            // Ignore ConnectionAborted -- this means that the server closed
+            // the connection after responding.
+            Ok(Err(ref e)) if e.kind() == std::io::ErrorKind::ConnectionAborted => (),
+            Ok(Err(e)) => return Err(e).with_context(|| "TLS read error"),
+            Err(_) => return Err(anyhow!("read timed out")),
+        }
+
+        gemini::parse_response(&plaintext).with_context(|| "failed to parse response")
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use rustls::{Certificate, RootCertStore, ServerCertVerified, ServerCertVerifier, TLSError};
+use sha2::{Digest, Sha256};
+
+/// Accepts any certificate presented by the server without verification.
+///
+/// This is only suitable for quick, throwaway connections; prefer
+/// `TofuVerifier` for anything that should be safe to reconnect to.
+pub struct NullVerifier;
+
+impl ServerCertVerifier for NullVerifier {
+    fn verify_server_cert(
+        &self,
+        _roots: &RootCertStore,
+        _presented_certs: &[Certificate],
+        _dns_name: webpki::DNSNameRef,
+        _ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+struct KnownHost {
+    fingerprint: String,
+    expiry: u64,
+}
+
+/// A `ServerCertVerifier` implementing Trust-On-First-Use (TOFU), the
+/// certificate model used by most Gemini clients and servers in place of a
+/// CA hierarchy.
+///
+/// The SHA-256 fingerprint of each host's leaf certificate is recorded in a
+/// `known_hosts`-style file (lines of `host fingerprint expiry`) the first
+/// time it is seen. Later connections are accepted only if the fingerprint
+/// still matches, or if the stored certificate has since expired, in which
+/// case it is treated as unseen and replaced.
+pub struct TofuVerifier {
+    known_hosts_path: PathBuf,
+    known_hosts: Mutex<HashMap<String, KnownHost>>,
+}
+
+impl TofuVerifier {
+    pub fn new(known_hosts_path: PathBuf) -> Result<Self> {
+        let known_hosts = load_known_hosts(&known_hosts_path)?;
+        Ok(TofuVerifier {
+            known_hosts_path,
+            known_hosts: Mutex::new(known_hosts),
+        })
+    }
+}
+
+impl ServerCertVerifier for TofuVerifier {
+    fn verify_server_cert(
+        &self,
+        _roots: &RootCertStore,
+        presented_certs: &[Certificate],
+        dns_name: webpki::DNSNameRef,
+        _ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        let host: &str = dns_name.into();
+        let leaf = presented_certs
+            .first()
+            .ok_or_else(|| TLSError::General("server presented no certificate".to_string()))?;
+
+        let fingerprint = fingerprint(leaf);
+        let expiry = cert_expiry(leaf)
+            .map_err(|e| TLSError::General(format!("failed to read certificate expiry: {}", e)))?;
+
+        let mut known_hosts = self.known_hosts.lock().unwrap();
+        match evaluate(host, known_hosts.get(host), &fingerprint, now()) {
+            Verdict::Reject(message) => return Err(TLSError::General(message)),
+            Verdict::Accept => return Ok(ServerCertVerified::assertion()),
+            Verdict::AcceptAndStore => (),
+        }
+
+        known_hosts.insert(host.to_string(), KnownHost { fingerprint, expiry });
+        persist_known_hosts(&self.known_hosts_path, &known_hosts)
+            .map_err(|e| TLSError::General(format!("failed to update known_hosts: {}", e)))?;
+
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Verdict {
+    /// The fingerprint matches the stored entry; nothing to persist.
+    Accept,
+    /// No entry yet, or the stored one has expired; store `fingerprint` for `host`.
+    AcceptAndStore,
+    Reject(String),
+}
+
+/// Decides whether a freshly-seen `fingerprint` should be accepted, given
+/// the (possibly absent) previously known host entry. Kept separate from
+/// `verify_server_cert` so the TOFU decision can be unit-tested without a
+/// live TLS handshake.
+fn evaluate(host: &str, known: Option<&KnownHost>, fingerprint: &str, now: u64) -> Verdict {
+    match known {
+        // The previously stored certificate has expired; treat this as a
+        // first sighting and replace it.
+        Some(known) if now >= known.expiry => Verdict::AcceptAndStore,
+        Some(known) if known.fingerprint == fingerprint => Verdict::Accept,
+        Some(known) => Verdict::Reject(format!(
+            "certificate fingerprint for {} does not match known_hosts entry \
+             (expected {}, got {}); possible man-in-the-middle attack",
+            host, known.fingerprint, fingerprint
+        )),
+        None => Verdict::AcceptAndStore,
+    }
+}
+
+fn fingerprint(cert: &Certificate) -> String {
+    let digest = Sha256::digest(&cert.0);
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn cert_expiry(cert: &Certificate) -> Result<u64> {
+    let (_, parsed) =
+        x509_parser::parse_x509_certificate(&cert.0).map_err(|e| anyhow::anyhow!("{}", e))?;
+    let timestamp = parsed.validity().not_after.timestamp();
+    Ok(timestamp.try_into().unwrap_or(0))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn load_known_hosts(path: &Path) -> Result<HashMap<String, KnownHost>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+
+    let mut known_hosts = HashMap::new();
+    for line in contents.lines() {
+        let mut fields = line.split_whitespace();
+        let host = fields.next().with_context(|| "malformed known_hosts line")?;
+        let fingerprint = fields
+            .next()
+            .with_context(|| "malformed known_hosts line")?;
+        let expiry = fields
+            .next()
+            .with_context(|| "malformed known_hosts line")?
+            .parse()
+            .with_context(|| "malformed known_hosts expiry")?;
+        known_hosts.insert(
+            host.to_string(),
+            KnownHost {
+                fingerprint: fingerprint.to_string(),
+                expiry,
+            },
+        );
+    }
+    Ok(known_hosts)
+}
+
+fn persist_known_hosts(path: &Path, known_hosts: &HashMap<String, KnownHost>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+
+    let mut contents = String::new();
+    for (host, known) in known_hosts {
+        contents.push_str(&format!("{} {} {}\n", host, known.fingerprint, known.expiry));
+    }
+
+    let mut file = fs::File::create(path)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    file.write_all(contents.as_bytes())
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{evaluate, fingerprint, load_known_hosts, persist_known_hosts, KnownHost, Verdict};
+    use rustls::Certificate;
+
+    #[test]
+    fn fingerprint_is_sha256_hex_of_cert_der() {
+        let cert = Certificate(vec![1, 2, 3]);
+        assert_eq!(
+            fingerprint(&cert),
+            "039058c6f2c0cb492c533b0a4d14ef77cc0f78abccced5287d84a1a2011cfb81"
+        );
+    }
+
+    #[test]
+    fn evaluate_accepts_and_stores_on_first_sight() {
+        assert_eq!(
+            evaluate("example.com", None, "abc", 1000),
+            Verdict::AcceptAndStore
+        );
+    }
+
+    #[test]
+    fn evaluate_accepts_matching_fingerprint() {
+        let known = KnownHost { fingerprint: "abc".to_string(), expiry: 2000 };
+        assert_eq!(evaluate("example.com", Some(&known), "abc", 1000), Verdict::Accept);
+    }
+
+    #[test]
+    fn evaluate_rejects_mismatched_fingerprint() {
+        let known = KnownHost { fingerprint: "abc".to_string(), expiry: 2000 };
+        match evaluate("example.com", Some(&known), "def", 1000) {
+            Verdict::Reject(message) => assert!(message.contains("does not match")),
+            other => panic!("expected Reject, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn evaluate_replaces_expired_entry_even_if_fingerprint_differs() {
+        let known = KnownHost { fingerprint: "abc".to_string(), expiry: 1000 };
+        assert_eq!(
+            evaluate("example.com", Some(&known), "def", 1000),
+            Verdict::AcceptAndStore
+        );
+    }
+
+    #[test]
+    fn known_hosts_roundtrip_through_persist_and_load() {
+        let dir = std::env::temp_dir().join(format!("gget-verifier-test-{}", std::process::id()));
+        let path = dir.join("known_hosts");
+
+        let mut known_hosts = std::collections::HashMap::new();
+        known_hosts.insert(
+            "example.com".to_string(),
+            KnownHost { fingerprint: "abc123".to_string(), expiry: 1234567890 },
+        );
+        persist_known_hosts(&path, &known_hosts).unwrap();
+
+        let loaded = load_known_hosts(&path).unwrap();
+        assert_eq!(loaded.get("example.com").unwrap().fingerprint, "abc123");
+        assert_eq!(loaded.get("example.com").unwrap().expiry, 1234567890);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}